@@ -1,21 +1,22 @@
 use std::{
+    collections::BTreeMap,
     future::Future,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
 };
 
-use futures::FutureExt;
+use futures::{future::try_join_all, FutureExt};
 use monero_serai::block::Block;
 use tower::{Service, ServiceExt};
 
 use monero_consensus::{
-    blocks::{calculate_pow_hash, check_block, check_block_pow},
+    blocks::{calculate_pow_hash, check_block, check_block_pow, RandomXVm},
     ConsensusError, HardFork,
 };
 
 use crate::{
-    context::{BlockChainContextRequest, BlockChainContextResponse},
+    context::{BlockChainContextRequest, BlockChainContextResponse, RawBlockChainContext},
     helper::rayon_spawn_async,
     transactions::{TransactionVerificationData, VerifyTxRequest, VerifyTxResponse},
     ExtendedConsensusError, TxNotInPool, TxPoolRequest, TxPoolResponse,
@@ -51,12 +52,30 @@ pub struct VerifiedBlockInformation {
 
 pub enum VerifyBlockRequest {
     MainChain(Block),
+    /// A batch of sequential main-chain blocks to prepare for verification.
+    ///
+    /// This only does the CPU-heavy, context-independent preparation (hashing, PoW) in
+    /// parallel; the cheap sequential checks that need the running context (difficulty,
+    /// parent linking) still have to be done afterwards, block by block.
+    MainChainBatch(Vec<Block>),
+    /// A block that may or may not extend the current main chain, verified against a
+    /// caller-supplied context instead of the one held by `context_svc`.
+    ///
+    /// RPC and P2P can both submit a valid block for the same height, and they won't always
+    /// agree; this lets a competing block be checked for validity on its own terms before
+    /// [`reorg_decision`] decides whether it should replace the current main chain.
+    AltChain {
+        block: Block,
+        alt_context: RawBlockChainContext,
+    },
 }
 
 pub enum VerifyBlockResponse {
     MainChain(VerifiedBlockInformation),
 
     BatchSetup(Vec<PrePreparedBlock>),
+
+    AltChain(VerifiedBlockInformation),
 }
 
 // TODO: it is probably a bad idea for this to derive clone, if 2 places (RPC, P2P) receive valid but different blocks
@@ -138,6 +157,12 @@ where
                 VerifyBlockRequest::MainChain(block) => {
                     verify_main_chain_block(block, context_svc, tx_verifier_svc, tx_pool).await
                 }
+                VerifyBlockRequest::MainChainBatch(blocks) => {
+                    verify_main_chain_block_batch(blocks, context_svc).await
+                }
+                VerifyBlockRequest::AltChain { block, alt_context } => {
+                    verify_alt_chain_block(block, alt_context, tx_verifier_svc, tx_pool).await
+                }
             }
         }
         .boxed()
@@ -177,6 +202,47 @@ where
 
     tracing::debug!("got blockchain context: {:?}", context);
 
+    let info = verify_block(block, context, tx_verifier_svc, tx_pool).await?;
+
+    Ok(VerifyBlockResponse::MainChain(info))
+}
+
+async fn verify_alt_chain_block<TxV, TxP>(
+    block: Block,
+    alt_context: RawBlockChainContext,
+    tx_verifier_svc: TxV,
+    tx_pool: TxP,
+) -> Result<VerifyBlockResponse, ExtendedConsensusError>
+where
+    TxV: Service<VerifyTxRequest, Response = VerifyTxResponse, Error = ExtendedConsensusError>,
+    TxP: Service<TxPoolRequest, Response = TxPoolResponse, Error = TxNotInPool>
+        + Clone
+        + Send
+        + 'static,
+{
+    tracing::debug!("verifying alt chain block against supplied context: {:?}", alt_context);
+
+    let info = verify_block(block, alt_context, tx_verifier_svc, tx_pool).await?;
+
+    Ok(VerifyBlockResponse::AltChain(info))
+}
+
+/// Verifies `block` against `context`, which may be the current main-chain context or a
+/// caller-supplied alt-chain one; this function doesn't care which, it just checks the block is
+/// internally consistent with whatever context it's handed.
+async fn verify_block<TxV, TxP>(
+    block: Block,
+    context: RawBlockChainContext,
+    tx_verifier_svc: TxV,
+    tx_pool: TxP,
+) -> Result<VerifiedBlockInformation, ExtendedConsensusError>
+where
+    TxV: Service<VerifyTxRequest, Response = VerifyTxResponse, Error = ExtendedConsensusError>,
+    TxP: Service<TxPoolRequest, Response = TxPoolResponse, Error = TxNotInPool>
+        + Clone
+        + Send
+        + 'static,
+{
     let TxPoolResponse::Transactions(txs) = tx_pool
         .oneshot(TxPoolRequest::Transactions(block.txs.clone()))
         .await?;
@@ -208,14 +274,15 @@ where
     // do POW test last
     let chain_height = context.chain_height;
     let current_hf = context.current_hf;
-    let pow_hash =
-        rayon_spawn_async(move || calculate_pow_hash(&hashing_blob, chain_height, &current_hf))
-            .await
-            .map_err(ConsensusError::Block)?;
+    let pow_hash = rayon_spawn_async(move || {
+        calculate_pow_hash(None, &hashing_blob, chain_height, &current_hf)
+    })
+    .await
+    .map_err(ConsensusError::Block)?;
 
     check_block_pow(&pow_hash, context.next_difficulty).map_err(ConsensusError::Block)?;
 
-    Ok(VerifyBlockResponse::MainChain(VerifiedBlockInformation {
+    Ok(VerifiedBlockInformation {
         block_hash: block.hash(),
         block,
         txs,
@@ -226,5 +293,208 @@ where
         long_term_weight: context.next_block_long_term_weight(block_weight),
         hf_vote,
         cumulative_difficulty: context.cumulative_difficulty + context.next_difficulty,
-    }))
+    })
+}
+
+async fn verify_main_chain_block_batch<C>(
+    blocks: Vec<Block>,
+    context_svc: C,
+) -> Result<VerifyBlockResponse, ExtendedConsensusError>
+where
+    C: Service<
+        BlockChainContextRequest,
+        Response = BlockChainContextResponse,
+        Error = tower::BoxError,
+    >,
+{
+    tracing::debug!("getting blockchain context for batch setup");
+    let BlockChainContextResponse::Context(checked_context) = context_svc
+        .oneshot(BlockChainContextRequest::Get)
+        .await
+        .map_err(Into::<ExtendedConsensusError>::into)?
+    else {
+        panic!("Context service returned wrong response!");
+    };
+
+    let start_height = checked_context.unchecked_blockchain_context().chain_height;
+
+    let prepped_blocks = prepare_blocks_batch(blocks, start_height).await?;
+
+    Ok(VerifyBlockResponse::BatchSetup(prepped_blocks))
+}
+
+/// How often the RandomX seed (and therefore the initialized VM) changes.
+const RX_SEED_EPOCH: u64 = 2048;
+/// RandomX doesn't reseed right at the epoch boundary: the new seed only takes effect
+/// `RX_SEED_EPOCH_LAG` blocks later (`SEEDHASH_EPOCH_LAG` in monerod), so the switch to a new
+/// seed height (itself always a multiple of [`RX_SEED_EPOCH`]) trails the epoch boundary by this
+/// much.
+const RX_SEED_EPOCH_LAG: u64 = 64;
+
+/// Never split a seed group into sub-chunks smaller than this, no matter how many rayon workers
+/// are available, so a small or trailing batch doesn't dilute VM reuse down to one init per block.
+/// Only a group's last sub-chunk (or a group that is smaller than this to begin with) holds fewer.
+const MIN_BLOCKS_PER_RANDOMX_VM: usize = 16;
+
+/// The height of the block whose hash seeds the RandomX VM used to hash `height`.
+fn randomx_seed_height(height: u64) -> u64 {
+    if height <= RX_SEED_EPOCH + RX_SEED_EPOCH_LAG {
+        0
+    } else {
+        (height - RX_SEED_EPOCH_LAG - 1) & !(RX_SEED_EPOCH - 1)
+    }
+}
+
+/// Prepares a batch of sequential main-chain blocks, starting at `start_height`, for
+/// verification.
+///
+/// All the work done here is CPU-heavy and context-independent, so it's farmed out to rayon.
+/// RandomX only reseeds every [`RX_SEED_EPOCH`] blocks, so blocks are grouped by seed height
+/// first; each group is then split again into sub-chunks of [`MIN_BLOCKS_PER_RANDOMX_VM`] blocks
+/// or more, apart from the group's last sub-chunk (one per rayon worker for a large enough group), and
+/// every sub-chunk builds its own RandomX VM once and reuses it for every block it hashes. That
+/// keeps both halves of the speedup: hashing still fans out across every available core for
+/// large groups, but a small or trailing group isn't split finely enough to re-initialize a VM
+/// once per block.
+pub async fn prepare_blocks_batch(
+    blocks: Vec<Block>,
+    start_height: u64,
+) -> Result<Vec<PrePreparedBlock>, ConsensusError> {
+    let mut groups: BTreeMap<u64, Vec<(usize, Block)>> = BTreeMap::new();
+    for (i, block) in blocks.into_iter().enumerate() {
+        let height = start_height + i as u64;
+        groups
+            .entry(randomx_seed_height(height))
+            .or_default()
+            .push((i, block));
+    }
+
+    let num_workers = rayon::current_num_threads().max(1);
+
+    let mut chunks = Vec::new();
+    for (seed_height, mut group) in groups {
+        let chunk_size = (group.len() + num_workers - 1) / num_workers;
+        // RandomX VM init is expensive relative to hashing one block, so never split a group
+        // finer than this, even on a many-core host: a group with `len <= num_workers` would
+        // otherwise get one sub-chunk (and one VM init) per block, the exact per-block
+        // re-initialization the grouping exists to avoid.
+        let chunk_size = chunk_size.max(MIN_BLOCKS_PER_RANDOMX_VM);
+
+        while !group.is_empty() {
+            let at = chunk_size.min(group.len());
+            chunks.push((seed_height, group.drain(..at).collect::<Vec<_>>()));
+        }
+    }
+
+    let chunk_futures = chunks.into_iter().map(|(seed_height, chunk)| {
+        rayon_spawn_async(move || {
+            let randomx_vm =
+                RandomXVm::new_for_seed_height(seed_height).map_err(ConsensusError::Block)?;
+
+            chunk
+                .into_iter()
+                .map(|(i, block)| {
+                    prepare_block(block, start_height + i as u64, &randomx_vm)
+                        .map(|prepped| (i, prepped))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+    });
+
+    let mut prepped_blocks = try_join_all(chunk_futures)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    prepped_blocks.sort_unstable_by_key(|(i, _)| *i);
+
+    Ok(prepped_blocks.into_iter().map(|(_, block)| block).collect())
+}
+
+/// Which chain a freshly-verified block belongs in, decided by comparing accumulated work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockInsertedChain {
+    /// The alt chain the block extends doesn't (yet) outweigh the main chain; keep tracking it
+    /// as a candidate in case it gets extended further.
+    AltChain,
+    /// The alt chain the block extends has overtaken the main chain's cumulative difficulty; the
+    /// caller should reorg onto it.
+    AltChainReorg,
+}
+
+/// Compares a verified alt-chain block's cumulative difficulty against the main chain tip's to
+/// decide whether the alt chain should become the new main chain. The chain with the most
+/// accumulated proof-of-work wins, same as the main-chain difficulty rule itself.
+pub fn reorg_decision(
+    main_chain_cumulative_difficulty: u128,
+    alt_block: &VerifiedBlockInformation,
+) -> BlockInsertedChain {
+    if alt_block.cumulative_difficulty > main_chain_cumulative_difficulty {
+        BlockInsertedChain::AltChainReorg
+    } else {
+        BlockInsertedChain::AltChain
+    }
+}
+
+/// Tracks alt-chain blocks competing with the main chain, keyed by height, so a later block can
+/// be checked against the tip of whichever alt chain it extends instead of only the main chain.
+#[derive(Debug, Default)]
+pub struct AltChainCandidates {
+    by_height: BTreeMap<u64, Vec<VerifiedBlockInformation>>,
+}
+
+impl AltChainCandidates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a verified alt-chain block as a candidate at its height.
+    pub fn insert(&mut self, alt_block: VerifiedBlockInformation) {
+        self.by_height
+            .entry(alt_block.height)
+            .or_default()
+            .push(alt_block);
+    }
+
+    /// Returns the tracked alt-chain candidates at `height`, if any.
+    pub fn candidates_at(&self, height: u64) -> &[VerifiedBlockInformation] {
+        self.by_height
+            .get(&height)
+            .map_or(&[], |candidates| candidates.as_slice())
+    }
+
+    /// Drops every tracked candidate at or below `height`, called once a reorg to that height (or
+    /// past it) has happened and the candidates are no longer relevant.
+    pub fn prune_up_to(&mut self, height: u64) {
+        self.by_height.retain(|candidate_height, _| *candidate_height > height);
+    }
+}
+
+fn prepare_block(
+    block: Block,
+    height: u64,
+    randomx_vm: &RandomXVm,
+) -> Result<PrePreparedBlock, ConsensusError> {
+    let hf_version = HardFork::from_version(&block.header.major_version)
+        .map_err(ConsensusError::Block)?;
+    let hf_vote = HardFork::from_vote(block.header.minor_version);
+
+    let block_blob = block.serialize();
+    let hashing_blob = block.serialize_hashable();
+
+    let pow_hash = calculate_pow_hash(Some(randomx_vm), &hashing_blob, height, &hf_version)
+        .map_err(ConsensusError::Block)?;
+    let block_hash = block.hash();
+    let miner_tx_weight = block.miner_tx.weight();
+
+    Ok(PrePreparedBlock {
+        block_hash,
+        pow_hash,
+        hf_vote,
+        hf_version,
+        miner_tx_weight,
+        block_blob,
+        block,
+    })
 }
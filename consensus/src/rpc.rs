@@ -1,19 +1,41 @@
+use std::collections::{BTreeMap, HashMap};
 use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use futures::lock::{OwnedMutexGuard, OwnedMutexLockFuture};
 use futures::{FutureExt, TryFutureExt};
+use monero_serai::block::BlockHeader;
 use monero_serai::rpc::{HttpRpc, RpcConnection, RpcError};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use thiserror::Error;
+use tower::ServiceExt;
 
 use cuprate_common::BlockID;
 
 use crate::pow::BlockPOWInfo;
 use crate::{DatabaseRequest, DatabaseResponse};
 
+/// Errors returned by [`Rpc`] and [`RpcBalancer`].
+#[derive(Debug, Clone, Error)]
+pub enum RpcServiceError {
+    #[error("RPC error: {0}")]
+    Rpc(#[from] RpcError),
+    #[error("block {0:?} not found")]
+    BlockNotFound(BlockID),
+    #[error("requested height is out of range")]
+    HeightOutOfRange,
+    #[error("failed to deserialize RPC response: {0}")]
+    Deserialize(String),
+    #[error("no RPC backends configured")]
+    NoBackends,
+}
+
 enum RpcState<R: RpcConnection> {
     Locked,
     Acquiring(OwnedMutexLockFuture<monero_serai::rpc::Rpc<R>>),
@@ -22,17 +44,15 @@ enum RpcState<R: RpcConnection> {
 pub struct Rpc<R: RpcConnection> {
     rpc: Arc<futures::lock::Mutex<monero_serai::rpc::Rpc<R>>>,
     rpc_state: RpcState<R>,
-    error_slot: Arc<Mutex<Option<RpcError>>>,
 }
 
 impl Rpc<HttpRpc> {
-    pub fn new_http(addr: String) -> Rpc<HttpRpc> {
-        let http_rpc = HttpRpc::new(addr).unwrap();
-        Rpc {
+    pub fn new_http(addr: String) -> Result<Rpc<HttpRpc>, RpcServiceError> {
+        let http_rpc = HttpRpc::new(addr).map_err(RpcServiceError::Rpc)?;
+        Ok(Rpc {
             rpc: Arc::new(futures::lock::Mutex::new(http_rpc)),
             rpc_state: RpcState::Locked,
-            error_slot: Arc::new(Mutex::new(None)),
-        }
+        })
     }
 }
 
@@ -41,21 +61,36 @@ impl<R: RpcConnection> Clone for Rpc<R> {
         Rpc {
             rpc: Arc::clone(&self.rpc),
             rpc_state: RpcState::Locked,
-            error_slot: Arc::clone(&self.error_slot),
         }
     }
 }
 
+/// The exact error text monerod's JSON-RPC layer returns for a missing block, used for every
+/// block lookup endpoint.
+/// `monero_serai`'s [`RpcError`] doesn't preserve the daemon's structured JSON-RPC error code, so
+/// this is the most precise match available without patching that crate; it's still an exact
+/// comparison of the whole message rather than a blanket substring scan, so an unrelated error that
+/// happens to mention "not found" elsewhere in its text (a connection failure, say) isn't
+/// misclassified as a missing block.
+const BLOCK_NOT_FOUND_MESSAGE: &str = "Block not found";
+
+/// Maps an [`RpcError`] coming back from a block lookup into a [`RpcServiceError`], recognising
+/// the daemon's "block not found" JSON-RPC response instead of treating it as a generic error.
+fn map_block_rpc_error(err: RpcError, id: BlockID) -> RpcServiceError {
+    if err.to_string().trim() == BLOCK_NOT_FOUND_MESSAGE {
+        RpcServiceError::BlockNotFound(id)
+    } else {
+        RpcServiceError::Rpc(err)
+    }
+}
+
 impl<R: RpcConnection + Send + Sync + 'static> tower::Service<DatabaseRequest> for Rpc<R> {
     type Response = DatabaseResponse;
-    type Error = tower::BoxError;
+    type Error = RpcServiceError;
     type Future =
         Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        if let Some(rpc_error) = self.error_slot.lock().unwrap().clone() {
-            return Poll::Ready(Err(rpc_error.into()));
-        }
         loop {
             match &mut self.rpc_state {
                 RpcState::Locked => {
@@ -75,54 +110,112 @@ impl<R: RpcConnection + Send + Sync + 'static> tower::Service<DatabaseRequest> f
             panic!("poll_ready was not called first!");
         };
 
-        let err_slot = self.error_slot.clone();
-
         match req {
             DatabaseRequest::ChainHeight => async move {
-                let res: Result<_, RpcError> = rpc
-                    .get_height()
-                    .map_ok(|height| DatabaseResponse::ChainHeight(height.try_into().unwrap()))
-                    .await;
-                if let Err(e) = &res {
-                    *err_slot.lock().unwrap() = Some(e.clone());
-                }
-                res.map_err(Into::into)
+                rpc.get_height().await.map_err(RpcServiceError::Rpc).and_then(
+                    |height| {
+                        height
+                            .try_into()
+                            .map(DatabaseResponse::ChainHeight)
+                            .map_err(|_| RpcServiceError::HeightOutOfRange)
+                    },
+                )
             }
             .boxed(),
 
             DatabaseRequest::BlockHeader(id) => match id {
                 BlockID::Hash(hash) => async move {
-                    let res: Result<_, RpcError> = rpc
-                        .get_block(hash)
+                    rpc.get_block(hash)
                         .map_ok(|block| DatabaseResponse::BlockHeader(block.header))
-                        .await;
-                    if let Err(e) = &res {
-                        *err_slot.lock().unwrap() = Some(e.clone());
-                    }
-                    res.map_err(Into::into)
+                        .await
+                        .map_err(|e| map_block_rpc_error(e, BlockID::Hash(hash)))
                 }
                 .boxed(),
                 BlockID::Height(height) => async move {
-                    let res: Result<_, RpcError> = rpc
-                        .get_block_by_number(height.try_into().unwrap())
-                        .map_ok(|block| DatabaseResponse::BlockHeader(block.header))
-                        .await;
-                    if let Err(e) = &res {
-                        *err_slot.lock().unwrap() = Some(e.clone());
-                    }
-                    res.map_err(Into::into)
+                    let height = height
+                        .try_into()
+                        .map_err(|_| RpcServiceError::HeightOutOfRange)?;
+                    rpc.get_block_by_number(height)
+                        .await
+                        .map(|block| DatabaseResponse::BlockHeader(block.header))
+                        .map_err(|e| map_block_rpc_error(e, BlockID::Height(height as u64)))
                 }
                 .boxed(),
             },
             DatabaseRequest::BlockPOWInfo(id) => get_blocks_pow_info(id, rpc).boxed(),
+
+            DatabaseRequest::BlockBatchInRange(range) => {
+                get_block_batch_in_range(range, rpc).boxed()
+            }
         }
     }
 }
 
+/// Maximum blocks pulled from `/get_blocks.bin` in a single round trip.
+const MAX_BLOCKS_PER_REQUEST: usize = 200;
+/// Target ceiling on raw bytes of block+tx data per round trip. Each request's length is sized
+/// from the bytes per block of the previous chunk (see [`get_block_batch_in_range`]); a chunk
+/// that still comes back over this is dropped and re-fetched at half the length, down to a
+/// single block.
+const MAX_BYTES_PER_REQUEST: usize = 64 * 1024 * 1024;
+
+/// Fetches `range` in chunks over the daemon's binary `/get_blocks.bin` endpoint, which returns
+/// full blocks and their transactions in one round trip instead of one `get_block` JSON call per
+/// block.
+async fn get_block_batch_in_range<R: RpcConnection>(
+    range: std::ops::Range<u64>,
+    rpc: OwnedMutexGuard<monero_serai::rpc::Rpc<R>>,
+) -> Result<DatabaseResponse, RpcServiceError> {
+    let heights: Vec<u64> = range.collect();
+    let mut blocks = Vec::with_capacity(heights.len());
+
+    // Recomputed after every chunk from that chunk's bytes per block, so the *next* round trip
+    // requests about as many heights as fit under `MAX_BYTES_PER_REQUEST`. The first round trip
+    // has nothing to go on and asks for `MAX_BLOCKS_PER_REQUEST`.
+    let mut next_len = MAX_BLOCKS_PER_REQUEST;
+    let mut cursor = 0;
+
+    while cursor < heights.len() {
+        let mut len = next_len.min(heights.len() - cursor).max(1);
+
+        let (chunk_blocks, chunk_bytes) = loop {
+            let chunk = &heights[cursor..cursor + len];
+            let chunk_blocks = rpc
+                .get_blocks(chunk.to_vec())
+                .await
+                .map_err(RpcServiceError::Rpc)?;
+
+            let chunk_bytes: usize = chunk_blocks
+                .iter()
+                .map(|(block, txs)| {
+                    block.serialize().len()
+                        + txs.iter().map(|tx| tx.serialize().len()).sum::<usize>()
+                })
+                .sum();
+
+            if chunk_bytes <= MAX_BYTES_PER_REQUEST || len == 1 {
+                break (chunk_blocks, chunk_bytes);
+            }
+
+            // Too heavy even before the next round trip could shrink to compensate: halve this
+            // request and re-fetch a smaller window instead of keeping the oversized one.
+            len = (len / 2).max(1);
+        };
+
+        cursor += len;
+        blocks.extend(chunk_blocks);
+
+        let bytes_per_block = (chunk_bytes / len).max(1);
+        next_len = (MAX_BYTES_PER_REQUEST / bytes_per_block).clamp(1, MAX_BLOCKS_PER_REQUEST);
+    }
+
+    Ok(DatabaseResponse::BlockBatch(blocks))
+}
+
 async fn get_blocks_pow_info<R: RpcConnection>(
     id: BlockID,
     rpc: OwnedMutexGuard<monero_serai::rpc::Rpc<R>>,
-) -> Result<DatabaseResponse, tower::BoxError> {
+) -> Result<DatabaseResponse, RpcServiceError> {
     #[derive(Deserialize, Debug)]
     struct BlockHeaderResponse {
         cumulative_difficulty: u64,
@@ -135,38 +228,477 @@ async fn get_blocks_pow_info<R: RpcConnection>(
         block_header: BlockHeaderResponse,
     }
 
-    match id {
+    let res = match id {
         BlockID::Height(height) => {
-            let res = rpc
-                .json_rpc_call::<Response>(
-                    "get_block_header_by_height",
-                    Some(json!({"height": height})),
-                )
-                .await?;
-            Ok(DatabaseResponse::BlockPOWInfo(BlockPOWInfo {
-                timestamp: res.block_header.timestamp,
-                cumulative_difficulty: u128_from_low_high(
-                    res.block_header.cumulative_difficulty,
-                    res.block_header.cumulative_difficulty_top64,
-                ),
-            }))
+            rpc.json_rpc_call::<Response>(
+                "get_block_header_by_height",
+                Some(json!({"height": height})),
+            )
+            .await
         }
         BlockID::Hash(hash) => {
-            let res = rpc
-                .json_rpc_call::<Response>("get_block_header_by_hash", Some(json!({"hash": hash})))
-                .await?;
-            Ok(DatabaseResponse::BlockPOWInfo(BlockPOWInfo {
-                timestamp: res.block_header.timestamp,
-                cumulative_difficulty: u128_from_low_high(
-                    res.block_header.cumulative_difficulty,
-                    res.block_header.cumulative_difficulty_top64,
-                ),
-            }))
+            rpc.json_rpc_call::<Response>("get_block_header_by_hash", Some(json!({"hash": hash})))
+                .await
         }
     }
+    .map_err(|e| map_block_rpc_error(e, id))?;
+
+    Ok(DatabaseResponse::BlockPOWInfo(BlockPOWInfo {
+        timestamp: res.block_header.timestamp,
+        cumulative_difficulty: u128_from_low_high(
+            res.block_header.cumulative_difficulty,
+            res.block_header.cumulative_difficulty_top64,
+        ),
+    }))
 }
 
 fn u128_from_low_high(low: u64, high: u64) -> u128 {
     let res: u128 = high as u128;
     res << 64 | low as u128
 }
+
+/// Consecutive failures an endpoint can have before it's put on cooldown.
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// How long an endpoint is skipped after going unhealthy before it's tried again.
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+struct EndpointHealth {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+impl EndpointHealth {
+    fn new() -> Self {
+        EndpointHealth {
+            consecutive_failures: 0,
+            unhealthy_until: None,
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.unhealthy_until
+            .map_or(true, |until| Instant::now() >= until)
+    }
+
+    fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.unhealthy_until = None;
+    }
+
+    fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            self.unhealthy_until = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+        }
+    }
+}
+
+/// A [`tower::Service`] that balances [`DatabaseRequest`]s over several interchangeable [`Rpc`]
+/// backends.
+///
+/// Requests go to a healthy backend first; on an [`RpcError`] they are transparently retried
+/// against the next backend (healthy ones before unhealthy ones) before the error is surfaced.
+/// Any other error is a real answer from the daemon and is returned straight away, without
+/// counting against the backend's health. This way one dead or flapping daemon
+/// doesn't stall every request. An endpoint that fails repeatedly is taken out of rotation for
+/// [`UNHEALTHY_COOLDOWN`] and re-probed with the next request that reaches it, which is as cheap
+/// as a [`DatabaseRequest::ChainHeight`] call when that's what's actually being served.
+pub struct RpcBalancer<R: RpcConnection> {
+    rpcs: Arc<[Rpc<R>]>,
+    health: Arc<Vec<Mutex<EndpointHealth>>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl<R: RpcConnection> Clone for RpcBalancer<R> {
+    fn clone(&self) -> Self {
+        RpcBalancer {
+            rpcs: Arc::clone(&self.rpcs),
+            health: Arc::clone(&self.health),
+            next: Arc::clone(&self.next),
+        }
+    }
+}
+
+impl<R: RpcConnection> RpcBalancer<R> {
+    pub fn new(rpcs: Vec<Rpc<R>>) -> Self {
+        let health = rpcs.iter().map(|_| Mutex::new(EndpointHealth::new())).collect();
+
+        RpcBalancer {
+            rpcs: rpcs.into(),
+            health: Arc::new(health),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Backend indexes in dispatch order: healthy backends first, starting from the next
+    /// round-robin offset so load is spread across them, unhealthy backends last.
+    fn candidate_order(&self) -> Vec<usize> {
+        let len = self.rpcs.len();
+        if len == 0 {
+            return Vec::new();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % len;
+
+        let mut order: Vec<usize> = (0..len).map(|i| (start + i) % len).collect();
+        order.sort_by_key(|&i| !self.health[i].lock().unwrap().is_healthy());
+        order
+    }
+}
+
+impl<R: RpcConnection + Send + Sync + 'static> tower::Service<DatabaseRequest> for RpcBalancer<R> {
+    type Response = DatabaseResponse;
+    type Error = RpcServiceError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: DatabaseRequest) -> Self::Future {
+        let rpcs = Arc::clone(&self.rpcs);
+        let health = Arc::clone(&self.health);
+        let order = self.candidate_order();
+
+        async move {
+            let mut last_err = None;
+
+            for idx in order {
+                let mut rpc = rpcs[idx].clone();
+
+                let res = match rpc.ready().await {
+                    Ok(rpc) => rpc.call(req.clone()).await,
+                    Err(e) => Err(e),
+                };
+
+                match res {
+                    Ok(res) => {
+                        health[idx].lock().unwrap().record_success();
+                        return Ok(res);
+                    }
+                    Err(e @ RpcServiceError::Rpc(_)) => {
+                        health[idx].lock().unwrap().record_failure();
+                        last_err = Some(e);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Err(last_err.unwrap_or(RpcServiceError::NoBackends))
+        }
+        .boxed()
+    }
+}
+
+/// How long a cached [`DatabaseRequest::ChainHeight`] answer is served before going back to the
+/// wrapped service. Historical header/POW lookups don't need a TTL since their answers never
+/// change once the block they describe is mined; the chain tip does.
+const CHAIN_HEIGHT_TTL: Duration = Duration::from_secs(5);
+
+/// POW info is checkpointed to disk every `CHECKPOINT_INTERVAL` heights, analogous to the
+/// periodic CHT roots of a light-client header chain, so a restart only has to re-fetch the
+/// window since the last checkpoint instead of the whole POW/timestamp window the difficulty
+/// algorithm needs.
+const CHECKPOINT_INTERVAL: u64 = 2048;
+
+/// Trailing height window the difficulty algorithm actually reads (Monero's `DIFFICULTY_WINDOW`,
+/// padded generously). Heights older than this, relative to the newest one cached, are of no
+/// further use and are pruned so `pow_by_height`/`hash_to_height` stay a fixed size instead of
+/// growing for the life of the node.
+const POW_WINDOW: u64 = 2 * CHECKPOINT_INTERVAL;
+
+#[derive(Serialize, Deserialize)]
+struct PowCheckpoint {
+    entries: Vec<(u64, CheckpointedPowInfo)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CheckpointedPowInfo {
+    timestamp: u64,
+    cumulative_difficulty: u128,
+}
+
+impl From<&BlockPOWInfo> for CheckpointedPowInfo {
+    fn from(info: &BlockPOWInfo) -> Self {
+        CheckpointedPowInfo {
+            timestamp: info.timestamp,
+            cumulative_difficulty: info.cumulative_difficulty,
+        }
+    }
+}
+
+impl From<CheckpointedPowInfo> for BlockPOWInfo {
+    fn from(info: CheckpointedPowInfo) -> Self {
+        BlockPOWInfo {
+            timestamp: info.timestamp,
+            cumulative_difficulty: info.cumulative_difficulty,
+        }
+    }
+}
+
+/// A local cache of header/POW data sitting in front of another [`tower::Service<DatabaseRequest>`]
+/// (normally [`Rpc`] or [`RpcBalancer`]).
+///
+/// The difficulty algorithm repeatedly re-reads the same timestamp/cumulative-difficulty window
+/// as the chain tip advances, so [`DatabaseRequest::BlockPOWInfo`] is kept in an in-memory
+/// `BTreeMap` keyed by height, with a hash→height index for lookups by [`BlockID::Hash`]. Only
+/// the trailing [`POW_WINDOW`] heights are kept; older entries are pruned on insert since the
+/// difficulty algorithm never reads further back than that. [`DatabaseRequest::BlockHeader`] is
+/// cached the same way (same window, same hash→height index) since header and POW lookups cover
+/// the same trailing range during sync. A cache miss falls through to the wrapped service; the
+/// answer populates the cache only when the lookup was by [`BlockID::Height`], since an answer
+/// to a [`BlockID::Hash`] lookup doesn't carry the height to key it by (the hash→height index is
+/// filled only through [`CachedDatabase::note_block_hash`]). [`DatabaseRequest::ChainHeight`] is cached too, but only
+/// for [`CHAIN_HEIGHT_TTL`] since the chain tip, unlike already-mined blocks, keeps moving.
+pub struct CachedDatabase<S> {
+    inner: S,
+    pow_by_height: Arc<Mutex<BTreeMap<u64, BlockPOWInfo>>>,
+    header_by_height: Arc<Mutex<BTreeMap<u64, BlockHeader>>>,
+    hash_to_height: Arc<Mutex<HashMap<[u8; 32], u64>>>,
+    chain_height: Arc<Mutex<Option<(u64, Instant)>>>,
+    checkpoint_path: Option<PathBuf>,
+}
+
+impl<S: Clone> Clone for CachedDatabase<S> {
+    fn clone(&self) -> Self {
+        CachedDatabase {
+            inner: self.inner.clone(),
+            pow_by_height: Arc::clone(&self.pow_by_height),
+            header_by_height: Arc::clone(&self.header_by_height),
+            hash_to_height: Arc::clone(&self.hash_to_height),
+            chain_height: Arc::clone(&self.chain_height),
+            checkpoint_path: self.checkpoint_path.clone(),
+        }
+    }
+}
+
+impl<S> CachedDatabase<S> {
+    /// Wraps `inner`, optionally loading and persisting POW checkpoints at `checkpoint_path`.
+    pub fn new(inner: S, checkpoint_path: Option<PathBuf>) -> Self {
+        let pow_by_height = checkpoint_path
+            .as_deref()
+            .and_then(load_checkpoint)
+            .unwrap_or_default();
+
+        CachedDatabase {
+            inner,
+            pow_by_height: Arc::new(Mutex::new(pow_by_height)),
+            header_by_height: Arc::new(Mutex::new(BTreeMap::new())),
+            hash_to_height: Arc::new(Mutex::new(HashMap::new())),
+            chain_height: Arc::new(Mutex::new(None)),
+            checkpoint_path,
+        }
+    }
+
+    /// Registers the height a block hash was found at, so a later [`BlockID::Hash`] lookup for
+    /// the same block can be served from the height-keyed cache. Callers that already know both
+    /// (the block verifier, for one) should call this as blocks are processed.
+    pub fn note_block_hash(&self, hash: [u8; 32], height: u64) {
+        self.hash_to_height.lock().unwrap().insert(hash, height);
+    }
+}
+
+/// Loads every checkpoint batch written by [`append_checkpoint`] (one [`PowCheckpoint`] per
+/// line, oldest first) and folds them into a single map, then trims it down to [`POW_WINDOW`]
+/// since only the trailing window is ever read back out.
+fn load_checkpoint(path: &Path) -> Option<BTreeMap<u64, BlockPOWInfo>> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let mut pow_by_height = BTreeMap::new();
+
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        match serde_json::from_str::<PowCheckpoint>(line) {
+            Ok(checkpoint) => {
+                pow_by_height.extend(
+                    checkpoint
+                        .entries
+                        .into_iter()
+                        .map(|(height, info)| (height, info.into())),
+                );
+            }
+            Err(e) => {
+                tracing::warn!("failed to read POW checkpoint at {}: {e}", path.display());
+                return None;
+            }
+        }
+    }
+
+    if let Some(&newest) = pow_by_height.keys().next_back() {
+        prune_window(&mut pow_by_height, newest);
+    }
+    Some(pow_by_height)
+}
+
+/// Appends the entries newer than `since_height` as one new line, rather than rewriting the
+/// whole checkpoint file: each write is O(`CHECKPOINT_INTERVAL`) instead of O(heights synced so
+/// far), so the cost of checkpointing doesn't grow with how long the node has been syncing.
+fn append_checkpoint(path: &Path, pow_by_height: &BTreeMap<u64, BlockPOWInfo>, since_height: u64) {
+    use std::io::Write;
+
+    let checkpoint = PowCheckpoint {
+        entries: pow_by_height
+            .range((since_height + 1)..)
+            .map(|(height, info)| (*height, info.into()))
+            .collect(),
+    };
+
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|mut file| {
+            serde_json::to_writer(&mut file, &checkpoint).map_err(|e| e.to_string())?;
+            writeln!(file).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        tracing::warn!("failed to append POW checkpoint to {}: {e}", path.display());
+    }
+}
+
+/// Drops entries older than the trailing [`POW_WINDOW`] relative to `newest_height`, the only
+/// window the difficulty algorithm still has any use for.
+fn prune_window<V>(by_height: &mut BTreeMap<u64, V>, newest_height: u64) {
+    let cutoff = newest_height.saturating_sub(POW_WINDOW);
+    by_height.retain(|height, _| *height > cutoff);
+}
+
+fn lookup_by_id<V: Clone>(
+    id: &BlockID,
+    by_height: &Mutex<BTreeMap<u64, V>>,
+    hash_to_height: &Mutex<HashMap<[u8; 32], u64>>,
+) -> Option<V> {
+    let height = match id {
+        BlockID::Height(height) => *height,
+        BlockID::Hash(hash) => *hash_to_height.lock().unwrap().get(hash)?,
+    };
+    by_height.lock().unwrap().get(&height).cloned()
+}
+
+fn insert_pow_info(
+    height: u64,
+    info: BlockPOWInfo,
+    pow_by_height: &Mutex<BTreeMap<u64, BlockPOWInfo>>,
+    hash_to_height: &Mutex<HashMap<[u8; 32], u64>>,
+    checkpoint_path: Option<&Path>,
+) {
+    let mut pow_by_height = pow_by_height.lock().unwrap();
+    pow_by_height.insert(height, info);
+
+    if height % CHECKPOINT_INTERVAL == 0 {
+        if let Some(path) = checkpoint_path {
+            append_checkpoint(path, &pow_by_height, height.saturating_sub(CHECKPOINT_INTERVAL));
+        }
+    }
+
+    let newest = *pow_by_height.keys().next_back().unwrap_or(&height);
+    prune_window(&mut pow_by_height, newest);
+    let cutoff = newest.saturating_sub(POW_WINDOW);
+    hash_to_height.lock().unwrap().retain(|_, h| *h > cutoff);
+}
+
+impl<S> tower::Service<DatabaseRequest> for CachedDatabase<S>
+where
+    S: Service<DatabaseRequest, Response = DatabaseResponse, Error = RpcServiceError>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = DatabaseResponse;
+    type Error = RpcServiceError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: DatabaseRequest) -> Self::Future {
+        // Take the instance `poll_ready` was called on (an `Rpc` only accepts `call` once it has
+        // been polled ready) and leave a fresh clone behind for the next `poll_ready`.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        match req {
+            DatabaseRequest::ChainHeight => {
+                if let Some((height, fetched_at)) = *self.chain_height.lock().unwrap() {
+                    if fetched_at.elapsed() < CHAIN_HEIGHT_TTL {
+                        return async move { Ok(DatabaseResponse::ChainHeight(height)) }.boxed();
+                    }
+                }
+
+                let chain_height = Arc::clone(&self.chain_height);
+                async move {
+                    let res = inner.call(DatabaseRequest::ChainHeight).await?;
+                    if let DatabaseResponse::ChainHeight(height) = res {
+                        *chain_height.lock().unwrap() = Some((height, Instant::now()));
+                    }
+                    Ok(res)
+                }
+                .boxed()
+            }
+
+            DatabaseRequest::BlockPOWInfo(id) => {
+                if let Some(info) =
+                    lookup_by_id(&id, &self.pow_by_height, &self.hash_to_height)
+                {
+                    return async move { Ok(DatabaseResponse::BlockPOWInfo(info)) }.boxed();
+                }
+
+                let pow_by_height = Arc::clone(&self.pow_by_height);
+                let hash_to_height = Arc::clone(&self.hash_to_height);
+                let checkpoint_path = self.checkpoint_path.clone();
+                async move {
+                    let height = match id {
+                        BlockID::Height(height) => Some(height),
+                        BlockID::Hash(_) => None,
+                    };
+
+                    let res = inner.call(DatabaseRequest::BlockPOWInfo(id)).await?;
+                    if let (Some(height), DatabaseResponse::BlockPOWInfo(info)) = (height, &res) {
+                        insert_pow_info(
+                            height,
+                            info.clone(),
+                            &pow_by_height,
+                            &hash_to_height,
+                            checkpoint_path.as_deref(),
+                        );
+                    }
+                    Ok(res)
+                }
+                .boxed()
+            }
+
+            DatabaseRequest::BlockHeader(id) => {
+                if let Some(header) = lookup_by_id(&id, &self.header_by_height, &self.hash_to_height)
+                {
+                    return async move { Ok(DatabaseResponse::BlockHeader(header)) }.boxed();
+                }
+
+                let header_by_height = Arc::clone(&self.header_by_height);
+                async move {
+                    let height = match id {
+                        BlockID::Height(height) => Some(height),
+                        BlockID::Hash(_) => None,
+                    };
+
+                    let res = inner.call(DatabaseRequest::BlockHeader(id)).await?;
+                    if let (Some(height), DatabaseResponse::BlockHeader(header)) = (height, &res) {
+                        let mut header_by_height = header_by_height.lock().unwrap();
+                        header_by_height.insert(height, header.clone());
+                        let newest = *header_by_height.keys().next_back().unwrap_or(&height);
+                        prune_window(&mut header_by_height, newest);
+                    }
+                    Ok(res)
+                }
+                .boxed()
+            }
+
+            // A range of full blocks is too large and too rarely re-requested to be worth
+            // caching; just pass it straight through to the wrapped service.
+            DatabaseRequest::BlockBatchInRange(range) => {
+                async move { inner.call(DatabaseRequest::BlockBatchInRange(range)).await }.boxed()
+            }
+        }
+    }
+}